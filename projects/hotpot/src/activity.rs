@@ -10,13 +10,21 @@ use fitparser::Value;
 use flate2::read::GzDecoder;
 use geo::{EuclideanDistance, HaversineLength};
 use geo_types::{Coord, LineString, MultiLineString, Point};
+use quick_xml::events::Event;
+use quick_xml::Reader;
 use rusqlite::params;
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
-use crate::db::{encode_line, SqlDateTime};
+use crate::db::{encode_line, Codec, SqlDateTime};
+use crate::raster::{self, DEFAULT_STROKE_WIDTH};
 use crate::tile::{BBox, LngLat, Tile, WebMercator};
 use crate::{DEFAULT_TILE_EXTENT, DEFAULT_ZOOM_LEVELS};
 
+/// Zoom levels also cached as rasterized coverage, for heatmap renders that
+/// want a cheap additive blend instead of re-walking vector geometry.
+const DEFAULT_RASTER_ZOOMS: &[u8] = &[14, 15, 16];
+
 // TODO: not happy with the ergonomics of this.
 struct TileClipper {
     zoom: u8,
@@ -113,6 +121,17 @@ impl ClippedTiles {
             .filter(|(_, lines)| !lines.is_empty())
             .flat_map(|(tile, lines)| lines.iter().map(move |line| (tile, line)))
     }
+
+    /// Like [`Self::iter`], but grouped by tile so a tile crossed by more
+    /// than one segment (a loop, an out-and-back) is rasterized once with
+    /// all of its lines, instead of once per segment.
+    pub fn by_tile(&self) -> impl Iterator<Item = (&Tile, Vec<&LineString<u16>>)> {
+        self.0
+            .iter()
+            .flat_map(|clip| clip.tiles.iter())
+            .filter(|(_, lines)| !lines.is_empty())
+            .map(|(tile, lines)| (tile, lines.iter().collect()))
+    }
 }
 
 #[derive(Clone)]
@@ -214,8 +233,7 @@ where
     match kind {
         FileType::Gpx => parse_gpx(&mut reader),
         FileType::Fit => parse_fit(&mut reader),
-        // TODO: implement TCX
-        FileType::Tcx => Ok(None),
+        FileType::Tcx => parse_tcx(&mut reader),
     }
 }
 
@@ -339,6 +357,114 @@ fn parse_gpx<R: Read>(reader: &mut R) -> Result<Option<RawActivity>> {
     }))
 }
 
+/// Streams `Activities > Activity > Lap > Track > Trackpoint` without
+/// building a full DOM. Each `Track` starts a new segment. `Courses` reuses
+/// the same element names for non-activity data, so we track nesting under
+/// `Activities` and ignore anything outside it.
+fn parse_tcx<R: Read>(reader: &mut R) -> Result<Option<RawActivity>> {
+    let mut xml = Reader::from_reader(BufReader::new(reader));
+    xml.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut tag_stack: Vec<Vec<u8>> = vec![];
+    let mut in_position = false;
+    let mut in_activities = false;
+
+    let mut segments: Vec<LineString> = vec![];
+    let mut current_line: Vec<Coord> = vec![];
+
+    let (mut time, mut lat, mut lng) = (None::<String>, None::<f64>, None::<f64>);
+    let (mut start_time, mut end_time) = (None::<OffsetDateTime>, None::<OffsetDateTime>);
+
+    loop {
+        match xml.read_event_into(&mut buf)? {
+            Event::Eof => break,
+
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+
+                if name == b"Activities" {
+                    in_activities = true;
+                }
+
+                // Start a new segment at each lap/track boundary.
+                if in_activities && name == b"Track" && !current_line.is_empty() {
+                    segments.push(LineString::new(std::mem::take(&mut current_line)));
+                }
+
+                if in_activities && name == b"Position" {
+                    in_position = true;
+                }
+
+                tag_stack.push(name);
+            }
+
+            Event::Text(text) => {
+                if !in_activities {
+                    continue;
+                }
+
+                let Some(tag) = tag_stack.last() else {
+                    continue;
+                };
+
+                match tag.as_slice() {
+                    b"Time" => time = Some(text.unescape()?.into_owned()),
+                    b"LatitudeDegrees" if in_position => lat = text.unescape()?.parse().ok(),
+                    b"LongitudeDegrees" if in_position => lng = text.unescape()?.parse().ok(),
+                    _ => {}
+                }
+            }
+
+            Event::End(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                tag_stack.pop();
+
+                if name == b"Position" {
+                    in_position = false;
+                }
+
+                if in_activities && name == b"Trackpoint" {
+                    if let (Some(t), Some(lat), Some(lng)) = (time.take(), lat.take(), lng.take()) {
+                        if let Ok(ts) = OffsetDateTime::parse(&t, &Rfc3339) {
+                            start_time.get_or_insert(ts);
+                            end_time = Some(ts);
+                            current_line.push(Coord { x: lng, y: lat });
+                        }
+                    }
+                }
+
+                if name == b"Activities" {
+                    in_activities = false;
+                }
+            }
+
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    if !current_line.is_empty() {
+        segments.push(LineString::new(current_line));
+    }
+
+    if segments.is_empty() {
+        return Ok(None);
+    }
+
+    let duration_secs = start_time
+        .zip(end_time)
+        .map(|(start, end)| (end - start).whole_seconds().max(0) as u64);
+
+    Ok(Some(RawActivity {
+        title: None,
+        start_time: start_time.map(SqlDateTime),
+        duration_secs,
+        tracks: MultiLineString::new(segments),
+    }))
+}
+
 /// Allows us to treat `bar.gpx.gz` the same as `bar.gpx`.
 pub fn get_file_type(file_name: &str) -> Option<(FileType, CompressionType)> {
     let mut exts = file_name.rsplit('.');
@@ -361,6 +487,7 @@ pub fn upsert(
     name: &str,
     activity: &RawActivity,
     trim_dist: f64,
+    codec: Codec,
 ) -> Result<i64> {
     let mut insert_coords = conn.prepare_cached(
         "\
@@ -368,6 +495,12 @@ pub fn upsert(
         VALUES (?, ?, ?, ?, ?)",
     )?;
 
+    let mut insert_raster = conn.prepare_cached(
+        "\
+        INSERT INTO activity_tiles_raster (activity_id, z, x, y, coverage) \
+        VALUES (?, ?, ?, ?, ?)",
+    )?;
+
     // TODO: The `OR REPLACE` works for activities, but we'd still end up inserting the
     //   tiles again.
     conn.execute(
@@ -389,13 +522,37 @@ pub fn upsert(
     // TODO: encode multiline strings together in same blob?
     let tiles = activity.clip_to_tiles(&DEFAULT_ZOOM_LEVELS, trim_dist);
     for (tile, line) in tiles.iter() {
-        // TODO: can consider storing post rasterization for faster renders.
-        let simplified = simplify(&line.0, 4.0);
-        let encoded = encode_line(&simplified)?;
+        let simplified = LineString::new(simplify(&line.0, 4.0));
+        let encoded = encode_line(&simplified.0, codec)?;
 
         insert_coords.insert(params![activity_id, tile.z, tile.x, tile.y, encoded])?;
     }
 
+    // Rasterization is opt-in per zoom: the vector path above remains the
+    // source of truth, this is purely a cache for cheap renders. Grouped by
+    // tile (not by segment) so a tile crossed more than once -- a loop, an
+    // out-and-back -- gets exactly one coverage row instead of one per
+    // crossing.
+    for (tile, lines) in tiles.by_tile() {
+        if !DEFAULT_RASTER_ZOOMS.contains(&tile.z) {
+            continue;
+        }
+
+        let simplified: Vec<_> = lines
+            .iter()
+            .map(|line| LineString::new(simplify(&line.0, 4.0)))
+            .collect();
+        let coverage = raster::rasterize_tile(&simplified, DEFAULT_STROKE_WIDTH);
+
+        insert_raster.insert(params![
+            activity_id,
+            tile.z,
+            tile.x,
+            tile.y,
+            coverage.encode_rle()
+        ])?;
+    }
+
     Ok(activity_id)
 }
 
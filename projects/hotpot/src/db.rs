@@ -0,0 +1,287 @@
+//! Storage glue between in-memory activity data and SQLite: the coordinate
+//! blob codec used for `activity_tiles.coords`, and `rusqlite` trait impls
+//! for our date/time wrapper.
+
+use anyhow::{bail, Result};
+use geo_types::Coord;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use time::OffsetDateTime;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Wraps `OffsetDateTime` so we can implement `rusqlite`'s `ToSql`/`FromSql`
+/// for it without running afoul of the orphan rule.
+#[derive(Clone, Copy, Debug)]
+pub struct SqlDateTime(pub OffsetDateTime);
+
+impl ToSql for SqlDateTime {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0.unix_timestamp()))
+    }
+}
+
+impl FromSql for SqlDateTime {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let ts = value.as_i64()?;
+        OffsetDateTime::from_unix_timestamp(ts)
+            .map(SqlDateTime)
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+/// Compressor applied to an encoded coordinate blob before it's written to
+/// `activity_tiles.coords`, mirroring how an LSM engine selects a per-block
+/// `CompressionType`. Recorded as a tag byte in the blob's header so old
+/// rows keep decoding after the default changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Lz4,
+    Miniz,
+}
+
+impl Codec {
+    fn tag(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Lz4 => 1,
+            Codec::Miniz => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Lz4),
+            2 => Ok(Codec::Miniz),
+            _ => bail!("unknown coordinate blob codec tag {tag}"),
+        }
+    }
+
+    fn compress(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Codec::None => bytes.to_vec(),
+            Codec::Lz4 => lz4_flex::compress(bytes),
+            Codec::Miniz => miniz_oxide::deflate::compress_to_vec(bytes, 6),
+        }
+    }
+
+    fn decompress(self, bytes: &[u8], original_len: usize) -> Result<Vec<u8>> {
+        match self {
+            Codec::None => Ok(bytes.to_vec()),
+            Codec::Lz4 => lz4_flex::decompress(bytes, original_len)
+                .map_err(|e| anyhow::anyhow!("lz4 decompress failed: {e}")),
+            // `decompress_to_vec` has no output cap and will happily inflate
+            // a small stream into gigabytes, so bound it explicitly rather
+            // than relying on the caller's (attacker/corruption-controlled)
+            // `original_len` header field to limit the work done here.
+            Codec::Miniz => miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, MAX_RAW_LEN)
+                .map_err(|e| anyhow::anyhow!("miniz decompress failed: {e:?}")),
+        }
+    }
+}
+
+/// Codec used for newly written coordinate blobs. Swapping this only
+/// affects new writes -- [`decode_line`] dispatches on the tag stored in
+/// each row, so existing data keeps working without a migration.
+pub const DEFAULT_CODEC: Codec = Codec::Lz4;
+
+/// Fixed-size header written ahead of every coordinate blob: codec tag,
+/// uncompressed length, and an `xxh3` checksum of the uncompressed bytes.
+struct Header {
+    codec: Codec,
+    original_len: u32,
+    checksum: u64,
+}
+
+const HEADER_LEN: usize = 1 + 4 + 8;
+
+impl Header {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.codec.tag());
+        out.extend_from_slice(&self.original_len.to_le_bytes());
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+    }
+
+    fn read(bytes: &[u8]) -> Result<(Self, &[u8])> {
+        if bytes.len() < HEADER_LEN {
+            bail!("coordinate blob shorter than its header");
+        }
+
+        let (header, body) = bytes.split_at(HEADER_LEN);
+        let codec = Codec::from_tag(header[0])?;
+        let original_len = u32::from_le_bytes(header[1..5].try_into().unwrap());
+        let checksum = u64::from_le_bytes(header[5..13].try_into().unwrap());
+
+        Ok((
+            Header {
+                codec,
+                original_len,
+                checksum,
+            },
+            body,
+        ))
+    }
+}
+
+/// Serialize a simplified tile polyline (pixel-space `u16` coordinates) to
+/// the blob format stored in `activity_tiles.coords`: a checksummed,
+/// codec-tagged wrapper around a flat little-endian point list.
+pub fn encode_line(coords: &[Coord<u16>], codec: Codec) -> Result<Vec<u8>> {
+    let mut raw = Vec::with_capacity(coords.len() * 4);
+    for c in coords {
+        raw.extend_from_slice(&c.x.to_le_bytes());
+        raw.extend_from_slice(&c.y.to_le_bytes());
+    }
+
+    let header = Header {
+        codec,
+        original_len: raw.len() as u32,
+        checksum: xxh3_64(&raw),
+    };
+    let compressed = codec.compress(&raw);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + compressed.len());
+    header.write(&mut out);
+    out.extend_from_slice(&compressed);
+
+    Ok(out)
+}
+
+/// Upper bound on a single tile's decompressed blob. Coordinate lists for
+/// one tile are never anywhere near this large; it exists purely so a
+/// corrupted `original_len` header field can't be used to make us
+/// preallocate an unreasonable amount of memory before the checksum below
+/// has a chance to reject the row.
+const MAX_RAW_LEN: usize = 16 * 1024 * 1024;
+
+/// Inverse of [`encode_line`]. Dispatches on the stored codec tag so rows
+/// written before a codec change keep decoding, and rejects silently
+/// corrupted rows via the stored checksum rather than handing back bogus
+/// pixel coordinates.
+pub fn decode_line(bytes: &[u8]) -> Result<Vec<Coord<u16>>> {
+    let (header, body) = Header::read(bytes)?;
+
+    if header.original_len as usize > MAX_RAW_LEN {
+        bail!(
+            "coordinate blob declares {} decompressed bytes, refusing to allocate (max {})",
+            header.original_len,
+            MAX_RAW_LEN
+        );
+    }
+
+    let raw = header
+        .codec
+        .decompress(body, header.original_len as usize)?;
+
+    if raw.len() != header.original_len as usize {
+        bail!(
+            "decompressed {} bytes, header declared {}",
+            raw.len(),
+            header.original_len
+        );
+    }
+
+    if xxh3_64(&raw) != header.checksum {
+        bail!("coordinate blob failed checksum verification, data is corrupt");
+    }
+
+    if raw.len() % 4 != 0 {
+        bail!(
+            "decoded {} bytes, not a multiple of point size 4",
+            raw.len()
+        );
+    }
+
+    raw.chunks_exact(4)
+        .map(|p| {
+            let x = u16::from_le_bytes([p[0], p[1]]);
+            let y = u16::from_le_bytes([p[2], p[3]]);
+            Ok(Coord { x, y })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample_coords() -> Vec<Coord<u16>> {
+        vec![
+            Coord { x: 0, y: 0 },
+            Coord { x: 10, y: 20 },
+            Coord { x: 4096, y: 1 },
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip_default_codec() {
+        let coords = sample_coords();
+        let encoded = encode_line(&coords, DEFAULT_CODEC).unwrap();
+        assert_eq!(decode_line(&encoded).unwrap(), coords);
+    }
+
+    #[test]
+    fn test_decode_detects_corruption() {
+        let coords = sample_coords();
+        let mut encoded = encode_line(&coords, DEFAULT_CODEC).unwrap();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 0xff;
+
+        assert!(decode_line(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_old_none_tagged_rows_still_decode() {
+        let coords = sample_coords();
+        let mut raw = Vec::new();
+        for c in &coords {
+            raw.extend_from_slice(&c.x.to_le_bytes());
+            raw.extend_from_slice(&c.y.to_le_bytes());
+        }
+
+        let header = Header {
+            codec: Codec::None,
+            original_len: raw.len() as u32,
+            checksum: xxh3_64(&raw),
+        };
+
+        let mut legacy = Vec::new();
+        header.write(&mut legacy);
+        legacy.extend_from_slice(&raw);
+
+        assert_eq!(decode_line(&legacy).unwrap(), coords);
+    }
+
+    #[test]
+    fn test_roundtrip_miniz_codec() {
+        let coords = sample_coords();
+        let encoded = encode_line(&coords, Codec::Miniz).unwrap();
+        assert_eq!(decode_line(&encoded).unwrap(), coords);
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_declared_length() {
+        let coords = sample_coords();
+        let mut encoded = encode_line(&coords, DEFAULT_CODEC).unwrap();
+        let oversized = (MAX_RAW_LEN as u32 + 1).to_le_bytes();
+        encoded[1..5].copy_from_slice(&oversized);
+
+        assert!(decode_line(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_decode_rejects_misaligned_points() {
+        let raw = vec![0u8; 6]; // Not a multiple of the 4-byte point size.
+        let header = Header {
+            codec: Codec::None,
+            original_len: raw.len() as u32,
+            checksum: xxh3_64(&raw),
+        };
+
+        let mut encoded = Vec::new();
+        header.write(&mut encoded);
+        encoded.extend_from_slice(&raw);
+
+        assert!(decode_line(&encoded).is_err());
+    }
+}
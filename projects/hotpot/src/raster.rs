@@ -0,0 +1,267 @@
+//! Scanline rasterization of a tile's simplified polyline into a per-pixel
+//! alpha coverage grid, cached alongside the vector tile for cheap heatmap
+//! renders.
+
+use anyhow::{bail, Result};
+use geo_types::{Coord, LineString};
+
+use crate::DEFAULT_TILE_EXTENT;
+
+/// Stroke width, in tile pixels, used when no caller-specified width is
+/// given.
+pub const DEFAULT_STROKE_WIDTH: f32 = 1.5;
+
+/// Dense per-pixel alpha coverage for a single tile, resolved from signed
+/// cover/area accumulation at `DEFAULT_TILE_EXTENT` resolution.
+pub struct Coverage {
+    extent: u16,
+    alpha: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn extent(&self) -> u16 {
+        self.extent
+    }
+
+    pub fn alpha(&self, x: u16, y: u16) -> u8 {
+        self.alpha[y as usize * self.extent as usize + x as usize]
+    }
+
+    /// Run-length encode the dense alpha grid as repeated `(value, run_len)`
+    /// pairs: one value byte followed by a little-endian `u16` run length.
+    /// Coverage grids are mostly untouched pixels, so in practice this
+    /// collapses to a handful of runs per tile.
+    pub fn encode_rle(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pixels = self.alpha.iter().copied().peekable();
+
+        while let Some(value) = pixels.next() {
+            let mut run: u16 = 1;
+            while run < u16::MAX && pixels.peek() == Some(&value) {
+                pixels.next();
+                run += 1;
+            }
+
+            out.push(value);
+            out.extend_from_slice(&run.to_le_bytes());
+        }
+
+        out
+    }
+
+    /// Inverse of [`Coverage::encode_rle`].
+    pub fn decode_rle(extent: u16, bytes: &[u8]) -> Result<Self> {
+        let mut alpha = Vec::with_capacity(extent as usize * extent as usize);
+
+        for run in bytes.chunks(3) {
+            let [value, lo, hi] = run else {
+                bail!("truncated raster RLE run");
+            };
+            let len = u16::from_le_bytes([*lo, *hi]) as usize;
+            alpha.resize(alpha.len() + len, *value);
+        }
+
+        let expected = extent as usize * extent as usize;
+        if alpha.len() != expected {
+            bail!(
+                "decoded raster has {} pixels, expected {expected}",
+                alpha.len()
+            );
+        }
+
+        Ok(Self { extent, alpha })
+    }
+}
+
+/// Signed coverage accumulator for one tile: every stroked edge deposits a
+/// `cover` delta (the signed vertical fraction it crosses within a cell) and
+/// an `area` value (the signed trapezoidal coverage to the right of the edge
+/// within that cell) keyed by `(row, col)`. Most cells in a tile are never
+/// touched, so this stays sparse until [`Self::resolve`].
+#[derive(Default)]
+struct CoverageAccumulator {
+    cover: std::collections::HashMap<(u16, u16), f32>,
+    area: std::collections::HashMap<(u16, u16), f32>,
+}
+
+impl CoverageAccumulator {
+    fn deposit(&mut self, row: u16, col: u16, cover: f32, area: f32) {
+        *self.cover.entry((row, col)).or_default() += cover;
+        *self.area.entry((row, col)).or_default() += area;
+    }
+
+    /// Stroke a single line segment to `half_width` and deposit coverage for
+    /// every edge of the resulting ribbon quad.
+    fn deposit_segment(&mut self, extent: u16, a: Coord<f32>, b: Coord<f32>, half_width: f32) {
+        let quad = stroke_quad(a, b, half_width);
+        for i in 0..quad.len() {
+            let p0 = quad[i];
+            let p1 = quad[(i + 1) % quad.len()];
+            self.deposit_edge(extent, p0, p1);
+        }
+    }
+
+    /// Walk one polygon edge scanline by scanline, depositing one cover/area
+    /// contribution per scanline it crosses, into the column where the edge
+    /// sits at that band's vertical midpoint.
+    fn deposit_edge(&mut self, extent: u16, p0: Coord<f32>, p1: Coord<f32>) {
+        if (p0.y - p1.y).abs() < f32::EPSILON {
+            return; // Horizontal edges contribute no vertical crossing.
+        }
+
+        let dir = if p0.y < p1.y { 1.0 } else { -1.0 };
+        let (top, bottom) = if p0.y < p1.y { (p0, p1) } else { (p1, p0) };
+        let dxdy = (bottom.x - top.x) / (bottom.y - top.y);
+
+        let row_start = top.y.max(0.0).floor() as i32;
+        let row_end = bottom.y.min(extent as f32).ceil() as i32;
+
+        for row in row_start..row_end {
+            let band_top = (row as f32).max(top.y);
+            let band_bottom = ((row + 1) as f32).min(bottom.y);
+            let dy = band_bottom - band_top;
+            if dy <= 0.0 {
+                continue;
+            }
+
+            let x_mid = top.x + dxdy * ((band_top + band_bottom) * 0.5 - top.y);
+            let col = x_mid.floor().clamp(0.0, extent as f32 - 1.0);
+            let frac = x_mid - col;
+
+            let cover = dir * dy;
+            let area = cover * (1.0 - frac);
+
+            self.deposit(row as u16, col as u16, cover, area);
+        }
+    }
+
+    /// Left-to-right prefix sum of `cover` per scanline, combined with
+    /// `area`, yields the final `[0, 1]` alpha for every pixel. Cells
+    /// between two touched columns carry no `area` of their own, but still
+    /// need painting at whatever coverage the prefix sum has accumulated by
+    /// that point -- that's the interior of a fill/stroke, not just its
+    /// edges.
+    fn resolve(self, extent: u16) -> Coverage {
+        let mut alpha = vec![0u8; extent as usize * extent as usize];
+
+        let mut cols_by_row: std::collections::HashMap<u16, Vec<u16>> = Default::default();
+        for &(row, col) in self.cover.keys() {
+            cols_by_row.entry(row).or_default().push(col);
+        }
+
+        for (row, mut cols) in cols_by_row {
+            cols.sort_unstable();
+            cols.dedup();
+
+            let row_base = row as usize * extent as usize;
+            let mut running_cover = 0.0;
+            let mut prev_col = 0u16;
+
+            for col in cols {
+                let fill = running_cover.abs().clamp(0.0, 1.0);
+                if fill > 0.0 {
+                    for c in prev_col..col {
+                        alpha[row_base + c as usize] = (fill * 255.0).round() as u8;
+                    }
+                }
+
+                let cover = *self.cover.get(&(row, col)).unwrap_or(&0.0);
+                let area = *self.area.get(&(row, col)).unwrap_or(&0.0);
+
+                let a = (running_cover + area).abs().clamp(0.0, 1.0);
+                alpha[row_base + col as usize] = (a * 255.0).round() as u8;
+
+                running_cover += cover;
+                prev_col = col + 1;
+            }
+
+            let fill = running_cover.abs().clamp(0.0, 1.0);
+            if fill > 0.0 {
+                for c in prev_col..extent {
+                    alpha[row_base + c as usize] = (fill * 255.0).round() as u8;
+                }
+            }
+        }
+
+        Coverage { extent, alpha }
+    }
+}
+
+/// Extrude a line segment into a filled ribbon quad of the given half
+/// width, so a stroke can be rasterized with the same closed-polygon
+/// coverage algorithm used for fills.
+fn stroke_quad(a: Coord<f32>, b: Coord<f32>, half_width: f32) -> [Coord<f32>; 4] {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len = dx.hypot(dy).max(f32::EPSILON);
+    let (nx, ny) = (-dy / len * half_width, dx / len * half_width);
+
+    [
+        Coord {
+            x: a.x + nx,
+            y: a.y + ny,
+        },
+        Coord {
+            x: b.x + nx,
+            y: b.y + ny,
+        },
+        Coord {
+            x: b.x - nx,
+            y: b.y - ny,
+        },
+        Coord {
+            x: a.x - nx,
+            y: a.y - ny,
+        },
+    ]
+}
+
+/// Rasterize a tile's already tile-clipped lines into a dense alpha
+/// coverage grid at `DEFAULT_TILE_EXTENT` resolution.
+pub fn rasterize_tile(lines: &[LineString<u16>], stroke_width: f32) -> Coverage {
+    let extent = DEFAULT_TILE_EXTENT as u16;
+    let mut acc = CoverageAccumulator::default();
+
+    for line in lines {
+        for edge in line.0.windows(2) {
+            let [a, b] = edge else { continue };
+            let a = Coord {
+                x: a.x as f32,
+                y: a.y as f32,
+            };
+            let b = Coord {
+                x: b.x as f32,
+                y: b.y as f32,
+            };
+            acc.deposit_segment(extent, a, b, stroke_width / 2.0);
+        }
+    }
+
+    acc.resolve(extent)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rle_roundtrip() {
+        let coverage = Coverage {
+            extent: 4,
+            alpha: vec![0, 0, 255, 255, 0, 0, 0, 0, 128, 128, 128, 128, 0, 0, 0, 0],
+        };
+
+        let encoded = coverage.encode_rle();
+        let decoded = Coverage::decode_rle(4, &encoded).unwrap();
+
+        assert_eq!(decoded.alpha, coverage.alpha);
+    }
+
+    #[test]
+    fn test_rasterize_vertical_line_has_coverage() {
+        let line = LineString::from(vec![(10u16, 0u16), (10u16, 20u16)]);
+        let coverage = rasterize_tile(&[line], 2.0);
+
+        assert!(coverage.alpha(10, 10) > 0);
+        assert_eq!(coverage.alpha(0, 0), 0);
+    }
+}